@@ -7,22 +7,50 @@ use bevy::{
     asset::Assets,
     ecs::system::{Commands, ResMut},
     math::{primitives::Cuboid, Vec3},
-    pbr::{MeshMaterial3d, StandardMaterial},
     render::mesh::{Mesh, Mesh3d},
     transform::components::Transform,
     DefaultPlugins,
 };
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 
 use wasm_bindgen::prelude::*;
 
+mod instancing;
+use instancing::{
+    build_cube_instances, build_cube_instances_culled, CubeInstance, CubeInstances,
+    CubeInstancingPlugin,
+};
+
+use bevy::render::primitives::Frustum;
+use bevy::render::view::NoFrustumCulling;
+
+mod raymarch;
+use raymarch::{build_uniform, RayMarchMaterial, MAX_DIM};
+
+use std::collections::{HashMap, HashSet};
+
 const FRACTAL_DEF: [[u32; 4]; 4] = [[2, 3, 0, 1], [1, 2, 3, 0], [0, 1, 2, 3], [3, 0, 1, 2]];
 
 /// A marker component for our shapes so we can query them separately from the ground plane
 #[derive(Component)]
 struct Shape;
 
+/// Marker for the fullscreen quad used by the ray-marched render mode.
+#[derive(Component)]
+struct RayMarchQuad;
+
+/// Selects how the fractal is drawn: as spawned instanced geometry or as a
+/// fullscreen signed-distance field traced per pixel.
+#[derive(Resource, PartialEq, Eq, Clone, Copy)]
+enum RenderMode {
+    Instanced,
+    RayMarched,
+}
+
 #[derive(Resource)]
 struct FractalDepth {
     depth: u32,
@@ -33,67 +61,224 @@ struct FractalDef {
     value: Vec<Vec<u32>>,
 }
 
+/// A serializable snapshot of the current fractal, written to / read from disk
+/// so an interesting configuration can be saved and reloaded later.
+#[derive(Serialize, Deserialize)]
+struct FractalConfig {
+    depth: u32,
+    def: Vec<Vec<u32>>,
+}
+
+/// Checks the same square, non-empty, `1..=MAX_DIM` invariant the grid
+/// editor's Add/Remove Dimension buttons maintain; `build_cube_instances`
+/// divides by `def.len()` and spaces the columns by that same row count, so
+/// an empty definition would divide by zero and a non-square one would lay
+/// its columns out on the wrong scale.
+fn is_valid_fractal_def(def: &[Vec<u32>]) -> bool {
+    !def.is_empty() && def.len() <= MAX_DIM && def.iter().all(|row| row.len() == def.len())
+}
+
+/// Drives the morph between the currently-rendered fractal and a freshly
+/// requested one. Rather than hard-despawning and rebuilding on every slider
+/// move, a depth/definition change records the `start` instance buffer and the
+/// `target` one and lerps between them over [`Self::DURATION`] seconds, so appearing
+/// leaves grow in from zero scale and removed leaves shrink out.
+#[derive(Resource)]
+struct FractalAnimation {
+    timer: Timer,
+    active: bool,
+    start: Vec<CubeInstance>,
+    target: Vec<CubeInstance>,
+    last_depth: u32,
+    last_def: Vec<Vec<u32>>,
+}
+
+impl FractalAnimation {
+    const DURATION: f32 = 0.6;
+
+    fn new(depth: u32, def: Vec<Vec<u32>>) -> Self {
+        FractalAnimation {
+            timer: Timer::from_seconds(Self::DURATION, TimerMode::Once),
+            active: false,
+            start: Vec::new(),
+            target: Vec::new(),
+            last_depth: depth,
+            last_def: def,
+        }
+    }
+}
+
+/// Classic Hermite ease so the morph starts and ends gently.
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Quantized position key used to match a leaf in the start buffer with its
+/// counterpart in the target buffer across a depth change.
+fn instance_key(position: Vec3) -> (i64, i64, i64) {
+    let q = |v: f32| (v * 1000.0).round() as i64;
+    (q(position.x), q(position.y), q(position.z))
+}
+
+/// Builds the interpolated instance buffer for morph progress `t`: shared
+/// leaves lerp position and scale, new leaves grow from zero, and removed
+/// leaves shrink toward zero before disappearing.
+fn interpolate_instances(
+    start: &[CubeInstance],
+    target: &[CubeInstance],
+    t: f32,
+) -> Vec<CubeInstance> {
+    let start_map: HashMap<_, _> = start.iter().map(|c| (instance_key(c.position), c)).collect();
+    let target_keys: HashSet<_> = target.iter().map(|c| instance_key(c.position)).collect();
+
+    let mut out = Vec::with_capacity(target.len() + start.len());
+    for tc in target {
+        if let Some(sc) = start_map.get(&instance_key(tc.position)) {
+            out.push(CubeInstance {
+                position: sc.position.lerp(tc.position, t),
+                scale: sc.scale + (tc.scale - sc.scale) * t,
+            });
+        } else {
+            out.push(CubeInstance {
+                position: tc.position,
+                scale: tc.scale * t,
+            });
+        }
+    }
+    for sc in start {
+        if !target_keys.contains(&instance_key(sc.position)) {
+            out.push(CubeInstance {
+                position: sc.position,
+                scale: sc.scale * (1.0 - t),
+            });
+        }
+    }
+    out
+}
+
+/// Advances the active morph and writes the interpolated buffer into the
+/// fractal's [`CubeInstances`] each frame.
+fn animate_fractal(
+    time: Res<Time>,
+    mut animation: ResMut<FractalAnimation>,
+    mut query: Query<&mut CubeInstances, With<Shape>>,
+) {
+    if !animation.active {
+        return;
+    }
+    let Ok(mut instances) = query.get_single_mut() else {
+        return;
+    };
+
+    animation.timer.tick(time.delta());
+    let t = smoothstep(animation.timer.fraction());
+    instances.0 = interpolate_instances(&animation.start, &animation.target, t);
+
+    if animation.timer.finished() {
+        instances.0 = animation.target.clone();
+        animation.active = false;
+    }
+}
+
+/// Minimum projected size (bounding-sphere radius over distance to camera) a
+/// subtree must cover before we bother recursing into its children; smaller
+/// subtrees collapse to a single merged cube.
+const LOD_SCREEN_THRESHOLD: f32 = 0.01;
+
+const CONFIG_PATH: &str = "fractal.ron";
+
+/// Writes the current depth and definition to [`CONFIG_PATH`] as RON.
+fn save_fractal_config(depth: u32, def: &[Vec<u32>]) {
+    let config = FractalConfig {
+        depth,
+        def: def.to_vec(),
+    };
+    match File::create(CONFIG_PATH) {
+        Ok(file) => {
+            let writer = BufWriter::new(file);
+            if let Err(err) = ron::ser::to_writer_pretty(writer, &config, default()) {
+                error!("failed to serialize fractal config: {err}");
+            }
+        }
+        Err(err) => error!("failed to open {CONFIG_PATH} for writing: {err}"),
+    }
+}
+
+/// Reads a [`FractalConfig`] back from [`CONFIG_PATH`], if one exists and its
+/// definition is a valid shape (see [`is_valid_fractal_def`]).
+fn load_fractal_config() -> Option<FractalConfig> {
+    match File::open(CONFIG_PATH) {
+        Ok(file) => match ron::de::from_reader::<_, FractalConfig>(BufReader::new(file)) {
+            Ok(config) if is_valid_fractal_def(&config.def) => Some(config),
+            Ok(config) => {
+                error!(
+                    "{CONFIG_PATH} has an invalid fractal definition shape ({}x{}), ignoring",
+                    config.def.len(),
+                    config.def.first().map_or(0, Vec::len)
+                );
+                None
+            }
+            Err(err) => {
+                error!("failed to parse {CONFIG_PATH}: {err}");
+                None
+            }
+        },
+        Err(err) => {
+            error!("failed to open {CONFIG_PATH} for reading: {err}");
+            None
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub fn start() {
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugins(PanOrbitCameraPlugin)
         .add_plugins(EguiPlugin)
+        .add_plugins(CubeInstancingPlugin)
+        .add_plugins(MaterialPlugin::<RayMarchMaterial>::default())
+        .insert_resource(RenderMode::Instanced)
         .insert_resource(FractalDepth { depth: 0 })
         .insert_resource(FractalDef {
             value: FRACTAL_DEF.iter().map(|&x| x.to_vec()).collect(),
         })
+        .insert_resource(FractalAnimation::new(
+            0,
+            FRACTAL_DEF.iter().map(|&x| x.to_vec()).collect(),
+        ))
         .add_systems(Startup, setup)
-        .add_systems(Update, ui_fractal_depth)
+        .add_systems(
+            Update,
+            (pick_fractal_cell, ui_fractal_depth, animate_fractal).chain(),
+        )
+        .add_systems(Update, (sync_render_mode, update_raymarch_uniform))
         .run();
 }
 
-fn spawn_cube(
+/// Spawns the whole fractal as a single instanced-draw entity: one shared
+/// unit [`Cuboid`] mesh plus a flat [`CubeInstances`] buffer holding every
+/// leaf's translation and uniform scale. A depth-8 fractal is then one entity
+/// and one GPU buffer rather than 16^8 entities.
+fn spawn_fractal_instanced(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     scale: f32,
     position: Vec3,
+    depth: u32,
+    fractal_def: &[Vec<u32>],
 ) {
+    let instances = build_cube_instances(scale, position, depth, fractal_def);
     commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(scale, scale, scale))),
-        MeshMaterial3d::<StandardMaterial>(Default::default()),
-        Transform::from_translation(position),
+        Mesh3d(meshes.add(Cuboid::from_length(1.0))),
+        CubeInstances(instances),
+        Transform::default(),
+        NoFrustumCulling,
         Shape,
     ));
 }
 
-fn spawn_fractal_recursive(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    scale: f32,
-    position: Vec3,
-    n: u32,
-    fractal_def: &Vec<Vec<u32>>,
-) {
-    if n == 0 {
-        spawn_cube(commands, meshes, scale, position);
-    } else {
-        let new_scale = scale / fractal_def.len() as f32;
-        for i in 0..fractal_def.len() {
-            for j in 0..fractal_def[i].len() {
-                spawn_fractal_recursive(
-                    commands,
-                    meshes,
-                    new_scale,
-                    position
-                        + Vec3::new(
-                            i as f32 * new_scale,
-                            j as f32 * new_scale,
-                            fractal_def[i][j] as f32 * new_scale,
-                        ),
-                    n - 1,
-                    fractal_def,
-                );
-            }
-        }
-    }
-}
-
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -121,7 +306,7 @@ fn setup(
         Transform::from_xyz(8.0, 16.0, 8.0),
     ));
 
-    spawn_fractal_recursive(
+    spawn_fractal_instanced(
         &mut commands,
         &mut meshes,
         fractal_def.value.len() as f32,
@@ -135,77 +320,346 @@ fn ui_fractal_depth(
     mut contexts: EguiContexts,
     mut fractal_depth: ResMut<FractalDepth>,
     mut fractal_def: ResMut<FractalDef>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    query: Query<Entity, With<Shape>>,
+    mut animation: ResMut<FractalAnimation>,
+    mut render_mode: ResMut<RenderMode>,
+    query: Query<&CubeInstances, With<Shape>>,
+    camera: Query<(&Frustum, &GlobalTransform), With<PanOrbitCamera>>,
 ) {
     egui::Window::new("Fractal Definition").show(contexts.ctx_mut(), |ui| {
         ui.add(egui::Slider::new(&mut fractal_depth.depth, 0..=8).text("Fractal Depth"));
 
-        let mut fractal_def_input = build_fractal_def_str(&fractal_def);
         ui.horizontal(|ui| {
-            ui.label("Fractal Definition:");
-            ui.text_edit_singleline(&mut fractal_def_input);
+            ui.label("Render Mode:");
+            ui.radio_value(&mut *render_mode, RenderMode::Instanced, "Instanced");
+            ui.radio_value(&mut *render_mode, RenderMode::RayMarched, "Ray-marched");
         });
 
-        // allow the user to set each element of the fractal definition
-        let fractal_def_vec = str_to_fractal_def(fractal_def_input);
-        fractal_def.value = fractal_def_vec.clone();
+        // Edit each element of the fractal definition directly as a grid of
+        // drag values; the z-offset of every cell is bounded by the grid
+        // dimension, the same range `build_cube_instances` uses for a cell's
+        // row and column steps.
+        ui.label("Fractal Definition:");
+        let dimension = fractal_def.value.len() as u32;
+        egui::Grid::new("fractal_def_grid").show(ui, |ui| {
+            for row in fractal_def.value.iter_mut() {
+                for cell in row.iter_mut() {
+                    ui.add(egui::DragValue::new(cell).range(0..=dimension.saturating_sub(1)));
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            // The definition is always a square `dimension x dimension` grid
+            // (`build_cube_instances` divides `scale` by the single row
+            // count and reuses it for the column axis), so rows and columns
+            // grow and shrink together rather than independently.
+            if ui.button("Add Dimension").clicked() && fractal_def.value.len() < MAX_DIM {
+                let new_dimension = fractal_def.value.len() + 1;
+                for row in fractal_def.value.iter_mut() {
+                    row.push(0);
+                }
+                fractal_def.value.push(vec![0; new_dimension]);
+            }
+            if ui.button("Remove Dimension").clicked() && fractal_def.value.len() > 1 {
+                fractal_def.value.pop();
+                for row in fractal_def.value.iter_mut() {
+                    row.pop();
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                save_fractal_config(fractal_depth.depth, &fractal_def.value);
+            }
+            if ui.button("Load").clicked() {
+                if let Some(config) = load_fractal_config() {
+                    fractal_depth.depth = config.depth;
+                    fractal_def.value = config.def;
+                }
+            }
+        });
     });
 
-    // input for dimension of the fractal definition
+    // Only kick off a new morph when the depth or definition actually changes,
+    // so the slider reads as an animation rather than a per-frame rebuild.
+    if fractal_depth.depth == animation.last_depth && fractal_def.value == animation.last_def {
+        return;
+    }
 
-    // Clear existing fractal shapes
-    for entity in query.iter() {
-        commands.entity(entity).despawn();
+    // Cull and LOD against the orbit camera when it is available, so depth 6-8
+    // never generates the sub-pixel geometry the camera can't meaningfully see.
+    let origin = Vec3::new(0.0, 0.0, 0.0);
+    let scale = fractal_def.value.len() as f32;
+    let target = match camera.get_single() {
+        Ok((frustum, transform)) => build_cube_instances_culled(
+            scale,
+            origin,
+            fractal_depth.depth,
+            &fractal_def.value,
+            frustum,
+            transform.translation(),
+            LOD_SCREEN_THRESHOLD,
+        ),
+        Err(_) => build_cube_instances(scale, origin, fractal_depth.depth, &fractal_def.value),
+    };
+    // Morph out of whatever is currently on screen (the partly-morphed buffer
+    // if a previous morph hasn't finished yet).
+    let start = query
+        .get_single()
+        .map(|instances| instances.0.clone())
+        .unwrap_or_default();
+
+    animation.start = start;
+    animation.target = target;
+    animation.timer.reset();
+    animation.active = true;
+    animation.last_depth = fractal_depth.depth;
+    animation.last_def = fractal_def.value.clone();
+}
+
+/// Spawns or despawns the fullscreen ray-march quad whenever [`RenderMode`]
+/// changes, and toggles the visibility of the instanced geometry so only the
+/// active mode is drawn.
+fn sync_render_mode(
+    mut commands: Commands,
+    render_mode: Res<RenderMode>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<RayMarchMaterial>>,
+    quad: Query<Entity, With<RayMarchQuad>>,
+    mut shapes: Query<&mut Visibility, With<Shape>>,
+) {
+    if !render_mode.is_changed() {
+        return;
     }
 
-    // Spawn fractal with updated depth
-    spawn_fractal_recursive(
-        &mut commands,
-        &mut meshes,
-        4.0,
-        Vec3::new(0.0, 0.0, 0.0),
-        fractal_depth.depth,
-        &fractal_def.value,
-    );
+    match *render_mode {
+        RenderMode::RayMarched => {
+            for mut visibility in &mut shapes {
+                *visibility = Visibility::Hidden;
+            }
+            if quad.is_empty() {
+                commands.spawn((
+                    Mesh3d(meshes.add(Rectangle::from_length(2.0))),
+                    MeshMaterial3d(materials.add(RayMarchMaterial::default())),
+                    // The quad is re-oriented to face the camera every frame; it
+                    // must never be culled by the camera's own frustum.
+                    NoFrustumCulling,
+                    RayMarchQuad,
+                ));
+            }
+        }
+        RenderMode::Instanced => {
+            for mut visibility in &mut shapes {
+                *visibility = Visibility::Inherited;
+            }
+            for entity in &quad {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
 }
 
-const FRACTAL_DEF_ELEMNET_SEPARATOR: &str = ",";
-const FRACTAL_DEF_ROW_SEPARATOR: &str = "|";
+/// Keeps the ray-march quad in front of the camera and refreshes its uniform
+/// with the current camera matrices and fractal definition each frame.
+fn update_raymarch_uniform(
+    render_mode: Res<RenderMode>,
+    fractal_depth: Res<FractalDepth>,
+    fractal_def: Res<FractalDef>,
+    camera: Query<(&Camera, &GlobalTransform), With<PanOrbitCamera>>,
+    light: Query<&GlobalTransform, With<PointLight>>,
+    mut materials: ResMut<Assets<RayMarchMaterial>>,
+    mut quad: Query<(&MeshMaterial3d<RayMarchMaterial>, &mut Transform), With<RayMarchQuad>>,
+) {
+    if *render_mode != RenderMode::RayMarched {
+        return;
+    }
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let light_position = light
+        .get_single()
+        .map(GlobalTransform::translation)
+        .unwrap_or(Vec3::ZERO);
+    // world_from_clip: invert clip_from_world (projection * view_from_world).
+    let clip_from_world =
+        camera.clip_from_view() * camera_transform.compute_matrix().inverse();
+    let inverse_view_proj = clip_from_world.inverse();
+
+    for (material_handle, mut transform) in &mut quad {
+        // Park the quad just in front of the near plane, facing the camera, so
+        // it always fills the viewport.
+        *transform = Transform::from_translation(
+            camera_transform.translation() + camera_transform.forward() * 0.1,
+        )
+        .looking_to(camera_transform.forward(), camera_transform.up());
 
-fn str_to_fractal_def(fractal_def_input: String) -> Vec<Vec<u32>> {
-    let mut fractal_def_vec: Vec<Vec<u32>> = vec![];
-    for (_i, row) in fractal_def_input
-        .split(FRACTAL_DEF_ROW_SEPARATOR)
-        .enumerate()
-    {
-        let mut row_vec: Vec<u32> = vec![];
-        for (_j, elem) in row.split(FRACTAL_DEF_ELEMNET_SEPARATOR).enumerate() {
-            if let Ok(num) = elem.trim().parse::<u32>() {
-                row_vec.push(num);
+        if let Some(material) = materials.get_mut(&material_handle.0) {
+            material.data = build_uniform(
+                inverse_view_proj,
+                camera_transform.translation(),
+                light_position,
+                fractal_depth.depth,
+                &fractal_def.value,
+            );
+        }
+    }
+}
+
+/// Picks a top-level fractal cell by raycasting from the cursor through the
+/// [`PanOrbitCamera`] against each top-level cell's AABB, taking the nearest
+/// cell the ray enters, and cycles that cell's z-offset on a left click.
+fn pick_fractal_cell(
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    camera: Query<(&Camera, &GlobalTransform), With<PanOrbitCamera>>,
+    mut fractal_def: ResMut<FractalDef>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    // Don't pick through the egui panel.
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+
+    // The top-level cells are unit cubes at (i, j, offset); find the nearest
+    // one the ray enters and advance its offset, wrapping at the dimension.
+    let dimension = fractal_def.value.len() as u32;
+    let origin = ray.origin;
+    let direction: Vec3 = ray.direction.into();
+
+    let mut hit: Option<(f32, usize, usize)> = None;
+    for (i, row) in fractal_def.value.iter().enumerate() {
+        for (j, &offset) in row.iter().enumerate() {
+            let min = Vec3::new(i as f32, j as f32, offset as f32) - Vec3::splat(0.5);
+            if let Some(t) = ray_aabb_entry(origin, direction, min, min + Vec3::ONE) {
+                if hit.map_or(true, |(best, _, _)| t < best) {
+                    hit = Some((t, i, j));
+                }
             }
         }
-        fractal_def_vec.push(row_vec);
     }
-    fractal_def_vec
+
+    if let Some((_, i, j)) = hit {
+        let cell = &mut fractal_def.value[i][j];
+        *cell = (*cell + 1) % dimension.max(1);
+    }
 }
 
-fn build_fractal_def_str(fractal_def: &ResMut<'_, FractalDef>) -> String {
-    let fractal_def_input = fractal_def
-        .value
-        .iter()
-        .map(|x| {
-            x.iter()
-                .map(|y| y.to_string())
-                .collect::<Vec<String>>()
-                .join(FRACTAL_DEF_ELEMNET_SEPARATOR)
-        })
-        .collect::<Vec<String>>()
-        .join(FRACTAL_DEF_ROW_SEPARATOR);
-    fractal_def_input
+/// Slab test for the entry distance of a ray into an axis-aligned box, or
+/// `None` when the ray misses.
+fn ray_aabb_entry(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<f32> {
+    let inv = direction.recip();
+    let t1 = (min - origin) * inv;
+    let t2 = (max - origin) * inv;
+    let t_enter = t1.min(t2).max_element();
+    let t_exit = t1.max(t2).min_element();
+    (t_exit >= t_enter.max(0.0)).then(|| t_enter.max(0.0))
 }
 
 fn main() {
     start()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(position: Vec3, scale: f32) -> CubeInstance {
+        CubeInstance { position, scale }
+    }
+
+    #[test]
+    fn is_valid_fractal_def_rejects_empty_and_non_square() {
+        assert!(!is_valid_fractal_def(&[]));
+        assert!(!is_valid_fractal_def(&[vec![0, 0], vec![0, 0, 0]]));
+        assert!(!is_valid_fractal_def(&vec![vec![0; 2]; MAX_DIM + 1]));
+        assert!(is_valid_fractal_def(&[vec![0, 1], vec![1, 0]]));
+    }
+
+    #[test]
+    fn interpolate_instances_grows_new_leaves_from_zero() {
+        let start: Vec<CubeInstance> = Vec::new();
+        let target = vec![cube(Vec3::new(1.0, 0.0, 0.0), 1.0)];
+
+        let mid = interpolate_instances(&start, &target, 0.5);
+        assert_eq!(mid.len(), 1);
+        assert_eq!(mid[0].position, target[0].position);
+        assert!((mid[0].scale - 0.5).abs() < 1e-6);
+
+        let end = interpolate_instances(&start, &target, 1.0);
+        assert!((end[0].scale - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn interpolate_instances_shrinks_removed_leaves_toward_zero() {
+        let start = vec![cube(Vec3::new(2.0, 0.0, 0.0), 1.0)];
+        let target: Vec<CubeInstance> = Vec::new();
+
+        let mid = interpolate_instances(&start, &target, 0.5);
+        assert_eq!(mid.len(), 1);
+        assert_eq!(mid[0].position, start[0].position);
+        assert!((mid[0].scale - 0.5).abs() < 1e-6);
+
+        let end = interpolate_instances(&start, &target, 1.0);
+        assert!((end[0].scale - 0.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn interpolate_instances_lerps_shared_leaves() {
+        let start = vec![cube(Vec3::new(0.0, 0.0, 0.0), 1.0)];
+        let target = vec![cube(Vec3::new(0.0, 0.0, 0.0), 2.0)];
+
+        let out = interpolate_instances(&start, &target, 0.5);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].position, Vec3::ZERO);
+        assert!((out[0].scale - 1.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ray_aabb_entry_hits_box_in_front_of_origin() {
+        let origin = Vec3::new(-5.0, 0.0, 0.0);
+        let direction = Vec3::X;
+        let min = Vec3::splat(-0.5);
+        let max = Vec3::splat(0.5);
+
+        let t = ray_aabb_entry(origin, direction, min, max).expect("ray should hit the box");
+        assert!((t - 4.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ray_aabb_entry_misses_box_off_to_the_side() {
+        let origin = Vec3::new(-5.0, 5.0, 0.0);
+        let direction = Vec3::X;
+        let min = Vec3::splat(-0.5);
+        let max = Vec3::splat(0.5);
+
+        assert_eq!(ray_aabb_entry(origin, direction, min, max), None);
+    }
+
+    #[test]
+    fn ray_aabb_entry_ignores_box_entirely_behind_origin() {
+        let origin = Vec3::new(5.0, 0.0, 0.0);
+        let direction = Vec3::X;
+        let min = Vec3::splat(-0.5);
+        let max = Vec3::splat(0.5);
+
+        assert_eq!(ray_aabb_entry(origin, direction, min, max), None);
+    }
+}
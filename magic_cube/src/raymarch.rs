@@ -0,0 +1,116 @@
+//! Ray-marched rendering mode.
+//!
+//! Instead of emitting cube geometry, this mode draws the fractal as a signed
+//! distance field in a fullscreen fragment shader: a single quad is sphere-
+//! traced per pixel against a recursive box-fold SDF parameterized by the
+//! [`FractalDef`](crate::FractalDef) offsets and
+//! [`FractalDepth`](crate::FractalDepth). Arbitrary depth then costs a fixed
+//! per-pixel loop rather than an ever-growing entity count.
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef, ShaderType},
+};
+
+/// Largest grid the ray-marched uniform can carry (a `DIM x DIM` definition).
+pub const MAX_DIM: usize = 4;
+
+/// Uniform block handed to the ray-march shader each frame.
+#[derive(Clone, Default, ShaderType)]
+pub struct RayMarchUniform {
+    /// Inverse view-projection, used to rebuild a world-space ray per pixel.
+    pub inverse_view_proj: Mat4,
+    /// Camera world position, `w` unused.
+    pub camera_position: Vec4,
+    pub depth: u32,
+    pub dimension: u32,
+    pub _padding: Vec2,
+    /// World-space position of the scene's `PointLight`, `w` unused.
+    pub light_position: Vec4,
+    /// Flattened per-cell z-offsets (`offsets[i * MAX_DIM + j].x`).
+    pub offsets: [Vec4; MAX_DIM * MAX_DIM],
+}
+
+/// Fullscreen material that sphere-traces the fractal SDF.
+#[derive(Asset, TypePath, AsBindGroup, Clone, Default)]
+pub struct RayMarchMaterial {
+    #[uniform(0)]
+    pub data: RayMarchUniform,
+}
+
+impl Material for RayMarchMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/raymarch.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/raymarch.wgsl".into()
+    }
+}
+
+/// Packs the current camera and fractal definition into a [`RayMarchUniform`].
+///
+/// `fractal_def` is expected to be at most `MAX_DIM x MAX_DIM`; the grid
+/// editor clamps to that bound, but rows/columns are still taken with
+/// `min(MAX_DIM)` here so a config loaded from disk can't overrun the fixed
+/// `offsets` storage or corrupt a neighboring row via the `i * MAX_DIM + j`
+/// packing stride.
+pub fn build_uniform(
+    inverse_view_proj: Mat4,
+    camera_position: Vec3,
+    light_position: Vec3,
+    depth: u32,
+    fractal_def: &[Vec<u32>],
+) -> RayMarchUniform {
+    let dimension = fractal_def.len().min(MAX_DIM);
+    let mut offsets = [Vec4::ZERO; MAX_DIM * MAX_DIM];
+    for (i, row) in fractal_def.iter().take(MAX_DIM).enumerate() {
+        for (j, &offset) in row.iter().take(MAX_DIM).enumerate() {
+            offsets[i * MAX_DIM + j] = Vec4::splat(offset as f32);
+        }
+    }
+    RayMarchUniform {
+        inverse_view_proj,
+        camera_position: camera_position.extend(1.0),
+        depth,
+        dimension: dimension as u32,
+        _padding: Vec2::ZERO,
+        light_position: light_position.extend(1.0),
+        offsets,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_uniform_packs_offsets_at_a_fixed_max_dim_stride() {
+        // A 2x2 definition: row 0 should land at indices 0 and 1
+        // (stride MAX_DIM, not the 2-wide row count), row 1 at MAX_DIM and
+        // MAX_DIM + 1, leaving every other slot zeroed.
+        let def = vec![vec![5, 6], vec![7, 8]];
+
+        let uniform = build_uniform(Mat4::IDENTITY, Vec3::ZERO, Vec3::ZERO, 3, &def);
+
+        assert_eq!(uniform.dimension, 2);
+        assert_eq!(uniform.offsets[0], Vec4::splat(5.0));
+        assert_eq!(uniform.offsets[1], Vec4::splat(6.0));
+        assert_eq!(uniform.offsets[MAX_DIM], Vec4::splat(7.0));
+        assert_eq!(uniform.offsets[MAX_DIM + 1], Vec4::splat(8.0));
+        assert_eq!(uniform.offsets[2], Vec4::ZERO);
+        assert_eq!(uniform.offsets[MAX_DIM * MAX_DIM - 1], Vec4::ZERO);
+    }
+
+    #[test]
+    fn build_uniform_clamps_an_oversized_definition_to_max_dim() {
+        let def = vec![vec![1; MAX_DIM + 2]; MAX_DIM + 2];
+
+        let uniform = build_uniform(Mat4::IDENTITY, Vec3::ZERO, Vec3::ZERO, 1, &def);
+
+        assert_eq!(uniform.dimension, MAX_DIM as u32);
+        for offset in &uniform.offsets {
+            assert_eq!(*offset, Vec4::splat(1.0));
+        }
+    }
+}